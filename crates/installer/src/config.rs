@@ -0,0 +1,110 @@
+// SPDX-FileCopyrightText: Copyright © 2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Declarative install manifest for unattended/headless installs
+//!
+//! A [`Manifest`] mirrors the fields an interactive install would ask
+//! for, but every field is optional: whatever is left unset here is
+//! simply left for `lichen_cli` to prompt for, so a partial manifest
+//! composes fine with the normal interactive flow. The one exception is
+//! `enable_ipv6`, which is only prompted for when `hostname` is unset.
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// Top-level manifest document, deserialized from TOML or JSON
+#[derive(Debug, Default, Deserialize)]
+pub struct Manifest {
+    /// Root filesystem partition to format and mount
+    pub partition: Option<PartitionSpec>,
+
+    /// EFI system partition to use for booting
+    pub boot_partition: Option<BootPartitionSpec>,
+
+    /// Accounts to create, in addition to root
+    #[serde(default)]
+    pub accounts: Vec<AccountSpec>,
+
+    /// Locale identifier, e.g. `en_US.UTF-8`
+    pub locale: Option<String>,
+
+    /// IANA timezone, e.g. `Europe/London`
+    pub timezone: Option<String>,
+
+    /// Machine hostname
+    pub hostname: Option<String>,
+
+    /// Whether to add an IPv6 loopback entry for the hostname
+    ///
+    /// Only read when `hostname` is set here, in which case leaving it
+    /// unset means `false`. Without a `hostname`, both are prompted for.
+    #[serde(default)]
+    pub enable_ipv6: bool,
+
+    /// Network management stack to enable, `network-manager` or
+    /// `systemd-networkd`
+    pub network_backend: Option<String>,
+
+    /// Named package selection groups to enable, resolved via
+    /// `selections::Manager::selections_with`
+    #[serde(default, alias = "packages")]
+    pub selections: Vec<String>,
+
+    /// Override the display manager selection group normally derived
+    /// from the chosen desktop (see `desktop::display_manager_for`)
+    pub display_manager: Option<String>,
+
+    /// Serial console to configure in the bootloader, e.g. `ttyS0,115200`
+    pub console: Option<String>,
+}
+
+/// Partition selection + desired on-disk layout
+#[derive(Debug, Deserialize)]
+pub struct PartitionSpec {
+    /// Block device to target, e.g. `/dev/sda2`
+    pub device: PathBuf,
+
+    /// Where the partition should be mounted
+    pub mountpoint: Option<PathBuf>,
+
+    /// Filesystem to create, e.g. `ext4`, `btrfs`
+    pub filesystem: String,
+}
+
+/// EFI system partition selection
+#[derive(Debug, Deserialize)]
+pub struct BootPartitionSpec {
+    /// Block device backing the ESP, e.g. `/dev/sda1`
+    pub device: PathBuf,
+}
+
+/// A single account to create during install
+#[derive(Debug, Deserialize)]
+pub struct AccountSpec {
+    /// Username for the account
+    pub username: String,
+
+    /// Login shell, defaults to `/usr/bin/bash` if unset (`root` keeps
+    /// its existing shell instead)
+    pub shell: Option<String>,
+
+    /// Plaintext password for the account, hashed before use
+    pub password: Option<String>,
+
+    /// Already-hashed password (e.g. `$6$...`), used as-is
+    pub password_hash: Option<String>,
+}
+
+impl Manifest {
+    /// Load and parse a manifest from `path`, dispatching on its
+    /// extension (`.toml` or `.json`, defaulting to TOML)
+    pub fn load(path: &Path) -> color_eyre::Result<Self> {
+        let contents = fs_err::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Ok(serde_json::from_str(&contents)?),
+            _ => Ok(toml::from_str(&contents)?),
+        }
+    }
+}
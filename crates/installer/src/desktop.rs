@@ -0,0 +1,34 @@
+// SPDX-FileCopyrightText: Copyright © 2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Desktop environment → display manager mapping
+
+/// A display manager: the package selection group that provides it and
+/// the systemd unit `lichen_cli` enables once it's installed
+#[derive(Debug, Clone, Copy)]
+pub struct DisplayManager {
+    /// Selection group name to pull in alongside the desktop, e.g. `gdm`
+    pub selection: &'static str,
+
+    /// systemd unit to enable in the target root, e.g. `gdm.service`
+    pub unit: &'static str,
+}
+
+/// Default desktop → display manager mapping
+///
+/// `selections::Group` names are used as the key, matching the names
+/// passed to `ask_desktop`/`selections_with`. Returns `None` for
+/// desktops that ship their own session management or aren't known.
+pub fn display_manager_for(desktop: &str) -> Option<DisplayManager> {
+    match desktop {
+        "gnome" => Some(DisplayManager { selection: "gdm", unit: "gdm.service" }),
+        "cosmic" => Some(DisplayManager {
+            selection: "cosmic-greeter",
+            unit: "cosmic-greeter.service",
+        }),
+        "kde" => Some(DisplayManager { selection: "sddm", unit: "sddm.service" }),
+        "xfce" | "mate" | "cinnamon" => Some(DisplayManager { selection: "lightdm", unit: "lightdm.service" }),
+        _ => None,
+    }
+}
@@ -0,0 +1,106 @@
+// SPDX-FileCopyrightText: Copyright © 2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Creating accounts and hashing their passwords
+
+use rand::Rng;
+use tokio::process::Command;
+
+use super::Context;
+use crate::Account;
+
+const SALT_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789./";
+const SALT_LEN: usize = 16;
+
+/// Hash a plaintext password with glibc's `$6$` SHA-512 crypt scheme
+///
+/// Strings that already start with `$` are assumed to be a prehashed
+/// password (e.g. supplied via `Account::with_hashed_password` from a
+/// manifest) and are returned unchanged, so we never double-hash.
+pub(crate) fn hash_password(password: &str) -> String {
+    if password.starts_with('$') {
+        return password.to_string();
+    }
+
+    let mut rng = rand::thread_rng();
+    let salt: String = (0..SALT_LEN)
+        .map(|_| SALT_CHARS[rng.gen_range(0..SALT_CHARS.len())] as char)
+        .collect();
+
+    let config = sha_crypt::Sha512Params::new(10_000).expect("valid SHA-512 crypt rounds");
+    sha_crypt::sha512_crypt(password, &salt, &config).expect("sha512_crypt with a valid salt")
+}
+
+/// Create an account in the target root and set its password
+#[derive(Debug)]
+pub struct CreateAccount {
+    pub(crate) account: Account,
+}
+
+impl<'a> CreateAccount {
+    /// Build a `CreateAccount` step for `account`
+    pub fn new(account: Account) -> Self {
+        Self { account }
+    }
+
+    pub async fn execute(&self, context: &impl Context<'a>) -> Result<(), super::Error> {
+        log::info!("Creating account {}", self.account.username);
+
+        let root = context.root().to_string_lossy().to_string();
+
+        // `root` always exists on a freshly installed rootfs, so there's
+        // nothing to `useradd`; we only need to set its shell/password.
+        if self.account.username == "root" {
+            if let Some(shell) = &self.account.shell {
+                let mut usermod = Command::new("usermod");
+                usermod.args(["--root", &root, "--shell", shell, "root"]);
+                let _ = context.run_command_captured(&mut usermod, None).await?;
+            }
+        } else {
+            let mut useradd = Command::new("useradd");
+            useradd.args(["--root", &root, "--create-home"]);
+            if let Some(shell) = &self.account.shell {
+                useradd.args(["--shell", shell]);
+            }
+            useradd.arg(&self.account.username);
+            let _ = context.run_command_captured(&mut useradd, None).await?;
+        }
+
+        if let Some(password) = &self.account.password {
+            let hashed = hash_password(password);
+            let mut chpasswd = Command::new("chpasswd");
+            chpasswd.args(["--root", &root, "--encrypted"]);
+            let input = format!("{}:{hashed}\n", self.account.username);
+            let _ = context.run_command_captured(&mut chpasswd, Some(&input)).await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn title(&self) -> String {
+        "Create account".into()
+    }
+
+    pub fn describe(&self) -> String {
+        self.account.username.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hash_password;
+
+    #[test]
+    fn hash_password_leaves_prehashed_passwords_untouched() {
+        let prehashed = "$6$somesalt$abcdefghijklmnopqrstuvwxyz";
+        assert_eq!(hash_password(prehashed), prehashed);
+    }
+
+    #[test]
+    fn hash_password_hashes_plaintext() {
+        let hashed = hash_password("hunter2");
+        assert!(hashed.starts_with("$6$"));
+        assert_ne!(hashed, "hunter2");
+    }
+}
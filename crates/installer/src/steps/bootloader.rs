@@ -0,0 +1,191 @@
+// SPDX-FileCopyrightText: Copyright © 2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Serial console / kernel cmdline injection into the bootloader config
+
+use std::{path::PathBuf, str::FromStr};
+
+use fs_err::tokio as fs;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use super::Context;
+
+const MARKER_START: &str = "# CONSOLE-SETTINGS-START";
+const MARKER_END: &str = "# CONSOLE-SETTINGS-END";
+
+/// Default bootloader config path (relative to the target root), used
+/// until bootloader installation reports a more specific one
+pub const DEFAULT_CONFIG_PATH: &str = "boot/grub/grub.cfg";
+
+static CONSOLE_BLOCK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(
+        r"(?s)(?P<prefix>{MARKER_START}\n)(?P<commands>.*?)(?P<suffix>{MARKER_END}\n)"
+    ))
+    .expect("valid console block regex")
+});
+
+/// A GRUB `linux`/`linuxefi` kernel command line, matched so the console's
+/// `console=` fragment can be spliced onto the end of it
+static LINUX_LINE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?m)^(\s*linux(?:efi)?\s+\S.*)$").expect("valid linux line regex")
+});
+
+/// A requested serial console, e.g. `console=ttyS0,115200n8`
+#[derive(Debug, Clone)]
+pub struct ConsoleSpec {
+    /// TTY device, e.g. `ttyS0`
+    pub(crate) device: String,
+
+    /// Baud rate, e.g. `115200`
+    pub(crate) baud: u32,
+}
+
+impl FromStr for ConsoleSpec {
+    type Err = String;
+
+    /// Parse `<device>,<baud>`, e.g. `ttyS0,115200`, defaulting to 115200
+    /// when the baud rate is omitted
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (device, baud) = match s.split_once(',') {
+            Some((device, baud)) => (
+                device,
+                baud.parse().map_err(|_| format!("invalid baud rate: {baud}"))?,
+            ),
+            None => (s, 115200),
+        };
+        Ok(ConsoleSpec { device: device.to_string(), baud })
+    }
+}
+
+impl ConsoleSpec {
+    /// Kernel cmdline fragment for this console, e.g. `console=ttyS0,115200n8`
+    fn kernel_cmdline(&self) -> String {
+        format!("console={},{}n8", self.device, self.baud)
+    }
+
+    /// Bootloader `serial`/`terminal` directives for this console
+    ///
+    /// `serial` must come first: GRUB rejects `terminal_input`/
+    /// `terminal_output serial` referencing a port that hasn't been
+    /// configured with `serial` yet.
+    fn bootloader_directives(&self) -> String {
+        format!(
+            "serial --unit=0 --speed={}\nterminal_input console serial\nterminal_output console serial\n",
+            self.baud
+        )
+    }
+}
+
+/// Edit a generated bootloader config to add serial console / kernel
+/// cmdline directives
+///
+/// The config is expected to live at `config_path` (relative to the
+/// target root). The `serial`/`terminal_*` directives go in a delimited
+/// block bounded by `# CONSOLE-SETTINGS-START` / `# CONSOLE-SETTINGS-END`
+/// markers, replaced in place (or appended, if the markers aren't present
+/// yet). The kernel cmdline fragment isn't valid as a bare top-level
+/// statement, so it's spliced onto the end of every `linux`/`linuxefi`
+/// line in the config instead; lines that already carry a `console=`
+/// argument are left as they are.
+#[derive(Debug)]
+pub struct ConfigureConsole {
+    /// Bootloader config file, relative to the target root
+    pub(crate) config_path: PathBuf,
+
+    /// Console to configure
+    pub(crate) console: ConsoleSpec,
+}
+
+impl<'a> ConfigureConsole {
+    /// Build a `ConfigureConsole` step for `console`, editing the
+    /// bootloader config at `config_path` (relative to the target root)
+    pub fn new(config_path: impl Into<PathBuf>, console: ConsoleSpec) -> Self {
+        Self { config_path: config_path.into(), console }
+    }
+
+    pub async fn execute(&self, context: &impl Context<'a>) -> Result<(), super::Error> {
+        log::info!("Configuring serial console on {}", self.console.device);
+
+        let path = context.root().join(&self.config_path);
+        let contents = fs::read_to_string(&path).await.unwrap_or_default();
+
+        let commands = self.console.bootloader_directives();
+        let updated = if CONSOLE_BLOCK.is_match(&contents) {
+            CONSOLE_BLOCK
+                .replace(&contents, |caps: &regex::Captures| {
+                    format!("{}{commands}{}", &caps["prefix"], &caps["suffix"])
+                })
+                .into_owned()
+        } else {
+            format!("{contents}\n{MARKER_START}\n{commands}{MARKER_END}\n")
+        };
+
+        let cmdline = self.console.kernel_cmdline();
+        let updated = LINUX_LINE
+            .replace_all(&updated, |caps: &regex::Captures| {
+                let line = &caps[1];
+                if line.contains("console=") {
+                    line.to_string()
+                } else {
+                    format!("{line} {cmdline}")
+                }
+            })
+            .into_owned();
+
+        fs::write(&path, updated).await?;
+        Ok(())
+    }
+
+    pub fn title(&self) -> String {
+        "Configure serial console".into()
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} @ {}", self.console.device, self.console.baud)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn console_spec_parses_device_and_baud() {
+        let spec: ConsoleSpec = "ttyS0,9600".parse().unwrap();
+        assert_eq!(spec.device, "ttyS0");
+        assert_eq!(spec.baud, 9600);
+    }
+
+    #[test]
+    fn console_spec_defaults_baud_when_omitted() {
+        let spec: ConsoleSpec = "ttyS0".parse().unwrap();
+        assert_eq!(spec.device, "ttyS0");
+        assert_eq!(spec.baud, 115200);
+    }
+
+    #[test]
+    fn console_spec_rejects_invalid_baud() {
+        assert!("ttyS0,not-a-number".parse::<ConsoleSpec>().is_err());
+    }
+
+    #[test]
+    fn console_block_does_not_match_without_markers() {
+        assert!(!CONSOLE_BLOCK.is_match("some existing grub.cfg content\n"));
+    }
+
+    #[test]
+    fn console_block_replaces_an_existing_block_in_place() {
+        let contents = format!("before\n{MARKER_START}\nold directives\n{MARKER_END}\nafter\n");
+        assert!(CONSOLE_BLOCK.is_match(&contents));
+
+        let replaced = CONSOLE_BLOCK
+            .replace(&contents, |caps: &regex::Captures| {
+                format!("{}new directives\n{}", &caps["prefix"], &caps["suffix"])
+            })
+            .into_owned();
+
+        assert_eq!(replaced, format!("before\n{MARKER_START}\nnew directives\n{MARKER_END}\nafter\n"));
+    }
+}
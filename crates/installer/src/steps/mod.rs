@@ -0,0 +1,56 @@
+// SPDX-FileCopyrightText: Copyright © 2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Install steps: the execution context they run against and the
+//! shared error type they return
+
+use std::{path::PathBuf, process::ExitStatus};
+
+use tokio::process::Command;
+
+pub mod accounts;
+pub mod bootloader;
+pub mod network;
+pub mod partitions;
+pub mod systemd;
+
+/// How many trailing lines of captured command output to keep around for
+/// [`Error::CommandFailed`]
+pub const ERROR_CONTEXT_LINES: usize = 20;
+
+/// Execution context a step runs against
+///
+/// Implementations stream each command's stdout/stderr line-by-line as
+/// it runs (so a runner can surface "last line" progress without
+/// fighting the raw terminal output) and are expected to tee the full
+/// output of every command into a persistent install log.
+pub trait Context<'a>: Sync {
+    /// Root of our install target
+    fn root(&'a self) -> &'a PathBuf;
+
+    /// Run a step command, discarding its output once it completes
+    async fn run_command(&self, cmd: &mut Command) -> Result<(), Error>;
+
+    /// Run a step command, returning its captured stdout/stderr
+    async fn run_command_captured(
+        &self,
+        cmd: &mut Command,
+        input: Option<&str>,
+    ) -> Result<std::process::Output, Error>;
+}
+
+/// Errors that can occur while executing an install step
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error("{program} failed with {status}:\n{}", .output.join("\n"))]
+    CommandFailed {
+        program: String,
+        status: ExitStatus,
+        /// Last [`ERROR_CONTEXT_LINES`] lines of combined stdout/stderr
+        output: Vec<String>,
+    },
+}
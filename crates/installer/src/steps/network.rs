@@ -0,0 +1,99 @@
+// SPDX-FileCopyrightText: Copyright © 2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Hostname and basic network configuration
+
+use std::str::FromStr;
+
+use fs_err::tokio as fs;
+
+use super::Context;
+
+/// Which network management stack to enable on the installed system
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkBackend {
+    /// NetworkManager, the usual choice for desktop installs
+    NetworkManager,
+    /// `systemd-networkd` (+ `systemd-resolved`), a lighter alternative
+    SystemdNetworkd,
+}
+
+impl NetworkBackend {
+    /// systemd units that need enabling for this backend, passed to
+    /// `steps::systemd::EnableUnit`
+    pub fn units(&self) -> &'static [&'static str] {
+        match self {
+            NetworkBackend::NetworkManager => &["NetworkManager.service"],
+            NetworkBackend::SystemdNetworkd => &["systemd-networkd.service", "systemd-resolved.service"],
+        }
+    }
+}
+
+impl FromStr for NetworkBackend {
+    type Err = String;
+
+    /// Parse `network-manager` or `systemd-networkd`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "network-manager" => Ok(Self::NetworkManager),
+            "systemd-networkd" => Ok(Self::SystemdNetworkd),
+            other => Err(format!("unknown network backend: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for NetworkBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkBackend::NetworkManager => write!(f, "NetworkManager"),
+            NetworkBackend::SystemdNetworkd => write!(f, "systemd-networkd"),
+        }
+    }
+}
+
+/// Write `/etc/hostname` and the matching loopback entries in `/etc/hosts`
+///
+/// `enable_ipv6` controls whether an `::1` entry is appended alongside
+/// the `127.0.1.1` one.
+#[derive(Debug)]
+pub struct SetHostname {
+    /// Hostname to install
+    pub(crate) hostname: String,
+
+    /// Whether to also add the IPv6 loopback entry
+    pub(crate) enable_ipv6: bool,
+}
+
+impl<'a> SetHostname {
+    /// Build a `SetHostname` step for `hostname`
+    pub fn new(hostname: impl Into<String>, enable_ipv6: bool) -> Self {
+        Self { hostname: hostname.into(), enable_ipv6 }
+    }
+
+    pub async fn execute(&self, context: &impl Context<'a>) -> Result<(), super::Error> {
+        log::info!("Setting hostname to {}", self.hostname);
+
+        let hostname_path = context.root().join("etc/hostname");
+        fs::write(&hostname_path, format!("{}\n", self.hostname)).await?;
+
+        let mut hosts = format!("127.0.1.1\t{}\n", self.hostname);
+        if self.enable_ipv6 {
+            hosts.push_str(&format!("::1\t{}\n", self.hostname));
+        }
+
+        let hosts_path = context.root().join("etc/hosts");
+        let existing = fs::read_to_string(&hosts_path).await.unwrap_or_default();
+        fs::write(&hosts_path, format!("{existing}{hosts}")).await?;
+
+        Ok(())
+    }
+
+    pub fn title(&self) -> String {
+        "Set hostname".into()
+    }
+
+    pub fn describe(&self) -> String {
+        self.hostname.clone()
+    }
+}
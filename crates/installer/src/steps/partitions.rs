@@ -25,14 +25,18 @@ pub struct FormatPartition<'a> {
 impl<'a> FormatPartition<'a> {
     pub(super) async fn execute(&self, context: &impl Context<'a>) -> Result<(), super::Error> {
         let fs = self.filesystem.to_lowercase();
-        let (exec, args) = match fs.as_str() {
-            "ext4" => ("mkfs.ext4", [&self.partition.path.display().to_string()]),
+        let path = self.partition.path.display().to_string();
+        let (exec, args): (_, Vec<String>) = match fs.as_str() {
+            "ext4" => ("mkfs.ext4", vec![path]),
+            "btrfs" => ("mkfs.btrfs", vec!["-f".into(), path]),
+            "xfs" => ("mkfs.xfs", vec!["-f".into(), path]),
+            "f2fs" => ("mkfs.f2fs", vec!["-f".into(), path]),
+            "vfat" => ("mkfs.vfat", vec!["-F32".into(), path]),
             _ => unimplemented!(),
         };
         log::info!("Formatting {} as {}", self.partition.path.display(), self.filesystem);
         log::trace!("Running: {exec:?} w/ {args:?}");
 
-        // For now we drop output, but we'll wire up stdout/stderr in context
         let mut cmd = Command::new(exec);
         cmd.args(args);
         let _ = context.run_command_captured(&mut cmd, None).await?;
@@ -44,8 +48,58 @@ impl<'a> FormatPartition<'a> {
     }
 
     pub(super) fn describe(&self) -> String {
-        // TODO: More than ext4 xD
-        format!("{} as ext4", self.partition.path.display())
+        format!("{} as {}", self.partition.path.display(), self.filesystem)
+    }
+}
+
+/// Create a layout of Btrfs subvolumes on a freshly formatted partition
+///
+/// The partition is temporarily mounted at `btrfs-staging` under
+/// `context.root()` to run `btrfs subvolume create` for each entry in
+/// `layout`, then unmounted again. Only the subvolume names are used
+/// here; the subvolume→mountpoint mapping stays in `layout` for the
+/// compile step to emit the right `subvol=` mounts from.
+#[derive(Debug)]
+pub struct CreateSubvolumes<'a> {
+    /// Partition to create the subvolumes on
+    pub(crate) partition: &'a Partition,
+
+    /// Subvolume name → mountpoint, e.g. `@` -> `/`, `@home` -> `/home`
+    pub(crate) layout: Vec<(String, PathBuf)>,
+}
+
+impl<'a> CreateSubvolumes<'a> {
+    pub(super) async fn execute(&self, context: &impl Context<'a>) -> Result<(), super::Error> {
+        let staging = context.root().join("btrfs-staging");
+        fs::create_dir_all(&staging).await?;
+
+        let source = self.partition.path.to_string_lossy().to_string();
+        let dest = staging.to_string_lossy().to_string();
+        let mut mount = Command::new("mount");
+        mount.args([&source, &dest]);
+        let _ = context.run_command_captured(&mut mount, None).await?;
+
+        for (subvolume, _) in &self.layout {
+            log::info!("Creating Btrfs subvolume {subvolume}");
+            let mut cmd = Command::new("btrfs");
+            cmd.args(["subvolume", "create", &staging.join(subvolume).to_string_lossy()]);
+            let _ = context.run_command_captured(&mut cmd, None).await?;
+        }
+
+        let mut umount = Command::new("umount");
+        umount.arg(&dest);
+        let _ = context.run_command_captured(&mut umount, None).await?;
+
+        Ok(())
+    }
+
+    pub(super) fn title(&self) -> String {
+        "Create subvolumes".into()
+    }
+
+    pub(super) fn describe(&self) -> String {
+        let names = self.layout.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+        format!("{} on {}", names.join(", "), self.partition.path.display())
     }
 }
 
@@ -57,6 +111,9 @@ pub struct MountPartition<'a> {
 
     /// Where are we mounting it?
     pub(crate) mountpoint: PathBuf,
+
+    /// Extra `-o` options, e.g. `subvol=@`, `compress=zstd`, `noatime`
+    pub(crate) options: Vec<String>,
 }
 
 impl<'a> MountPartition<'a> {
@@ -72,6 +129,9 @@ impl<'a> MountPartition<'a> {
         let source = self.partition.path.to_string_lossy().to_string();
         let dest = self.mountpoint.to_string_lossy().to_string();
         let mut cmd = Command::new("mount");
+        if !self.options.is_empty() {
+            cmd.args(["-o", &self.options.join(",")]);
+        }
         cmd.args([&source, &dest]);
 
         let _ = context.run_command_captured(&mut cmd, None).await?;
@@ -95,6 +155,9 @@ pub struct BindMount {
 
     /// Destination directory
     pub(crate) dest: PathBuf,
+
+    /// Extra `-o` options, e.g. `ro` for a read-only bind mount
+    pub(crate) options: Vec<String>,
 }
 
 impl<'a> BindMount {
@@ -106,7 +169,11 @@ impl<'a> BindMount {
         let source = self.source.to_string_lossy().to_string();
         let dest = self.dest.to_string_lossy().to_string();
         let mut cmd = Command::new("mount");
-        cmd.args(["--bind", &source, &dest]);
+        cmd.arg("--bind");
+        if !self.options.is_empty() {
+            cmd.args(["-o", &self.options.join(",")]);
+        }
+        cmd.args([&source, &dest]);
 
         let _ = context.run_command_captured(&mut cmd, None).await?;
         Ok(())
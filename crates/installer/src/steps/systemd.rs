@@ -0,0 +1,42 @@
+// SPDX-FileCopyrightText: Copyright © 2024 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Enabling systemd units inside the target root
+
+use tokio::process::Command;
+
+use super::Context;
+
+/// Enable a systemd unit in the target root via `systemctl --root`
+#[derive(Debug)]
+pub struct EnableUnit {
+    /// Unit name, e.g. `gdm.service`
+    pub(crate) unit: String,
+}
+
+impl<'a> EnableUnit {
+    /// Build an `EnableUnit` step for `unit`, e.g. `gdm.service`
+    pub fn new(unit: impl Into<String>) -> Self {
+        Self { unit: unit.into() }
+    }
+
+    pub async fn execute(&self, context: &impl Context<'a>) -> Result<(), super::Error> {
+        log::info!("Enabling {}", self.unit);
+
+        let root = context.root().to_string_lossy().to_string();
+        let mut cmd = Command::new("systemctl");
+        cmd.args(["--root", &root, "enable", &self.unit]);
+
+        let _ = context.run_command_captured(&mut cmd, None).await?;
+        Ok(())
+    }
+
+    pub fn title(&self) -> String {
+        "Enable service".into()
+    }
+
+    pub fn describe(&self) -> String {
+        self.unit.clone()
+    }
+}
@@ -6,28 +6,173 @@
 
 use std::{
     path::PathBuf,
-    process::{Output, Stdio},
+    process::{ExitStatus, Output, Stdio},
     str::FromStr,
     time::Duration,
 };
 
+use clap::Parser;
 use color_eyre::eyre::bail;
 use console::{set_colors_enabled, style};
 use crossterm::style::Stylize;
 use dialoguer::theme::ColorfulTheme;
 use indicatif::ProgressStyle;
 use installer::{
+    config::Manifest,
+    desktop,
     selections::{self, Group},
     steps::Context,
     systemd, Account, BootPartition, Installer, Locale, SystemPartition,
 };
 use nix::libc::geteuid;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 use tokio::process::Command;
 
-#[derive(Debug)]
+/// lichen: the Serpent OS installer
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Path to a TOML/JSON manifest to drive an unattended install
+    ///
+    /// Any field left unset in the manifest falls back to the normal
+    /// interactive prompts, so a partial manifest is fine. The exception
+    /// is `enable_ipv6`: with a manifest `hostname`, unset means `false`.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Configure a serial console for headless/VM installs, e.g. `ttyS0,115200`
+    #[arg(long)]
+    console: Option<installer::steps::bootloader::ConsoleSpec>,
+}
+
+/// A line of captured command output, tagged by which stream it came from
+enum StreamedLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+impl StreamedLine {
+    fn as_str(&self) -> &str {
+        match self {
+            StreamedLine::Stdout(line) | StreamedLine::Stderr(line) => line,
+        }
+    }
+}
+
+/// Last [`installer::steps::ERROR_CONTEXT_LINES`] lines of `output`,
+/// combined stdout/stderr in original stream order
+fn error_context(output: &[StreamedLine]) -> Vec<String> {
+    output
+        .iter()
+        .rev()
+        .take(installer::steps::ERROR_CONTEXT_LINES)
+        .rev()
+        .map(|line| line.as_str().to_string())
+        .collect()
+}
+
 struct CliContext {
     root: PathBuf,
+
+    /// Persistent install log, teed with the full output of every command
+    log: tokio::sync::Mutex<tokio::fs::File>,
+
+    /// Progress bar the currently-running step's output is routed to
+    active_bar: std::sync::Mutex<Option<indicatif::ProgressBar>>,
+
+    /// Printed to directly when there's no active bar to route output to,
+    /// so it's still visible above the progress bars rather than only
+    /// landing in the log
+    multi: indicatif::MultiProgress,
+}
+
+impl CliContext {
+    /// Open (or create) the install log under `root` and return a fresh context
+    async fn new(root: PathBuf, multi: indicatif::MultiProgress) -> color_eyre::Result<Self> {
+        let log_dir = root.join("var/log");
+        fs_err::tokio::create_dir_all(&log_dir).await?;
+        let log = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_dir.join("lichen-install.log"))
+            .await?;
+        Ok(Self {
+            root,
+            log: tokio::sync::Mutex::new(log),
+            active_bar: std::sync::Mutex::new(None),
+            multi,
+        })
+    }
+
+    /// Route subsequent streamed output lines to `bar`'s message, or print
+    /// them straight to the terminal if `None`
+    fn set_active_bar(&self, bar: Option<indicatif::ProgressBar>) {
+        *self.active_bar.lock().unwrap() = bar;
+    }
+
+    /// Tee a single output line into the install log and either the
+    /// active progress bar's status message, or (if there isn't one)
+    /// straight to the terminal, so steps without a spinner of their own
+    /// still show their output somewhere visible
+    async fn observe_line(&self, line: &str) {
+        let stamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+        let mut log = self.log.lock().await;
+        let _ = log.write_all(format!("[{stamp}] {line}\n").as_bytes()).await;
+
+        match self.active_bar.lock().unwrap().as_ref() {
+            Some(bar) => bar.set_message(line.to_string()),
+            None => {
+                let _ = self.multi.println(line);
+            }
+        }
+    }
+
+    /// Spawn `cmd`, streaming its stdout/stderr line-by-line through
+    /// [`Self::observe_line`] as it runs, and return the exit status plus
+    /// the captured output (full, in original stream order) once it
+    /// completes
+    async fn spawn_streamed(
+        &self,
+        cmd: &mut Command,
+        input: Option<&str>,
+    ) -> Result<(ExitStatus, Vec<StreamedLine>), installer::steps::Error> {
+        cmd.stdin(Stdio::piped());
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+        let mut ps = cmd.spawn()?;
+
+        if let Some(input) = input {
+            let mut stdin = ps.stdin.take().expect("stdin failure");
+            stdin.write_all(input.as_bytes()).await?;
+        }
+
+        let stdout = ps.stdout.take().expect("stdout failure");
+        let stderr = ps.stderr.take().expect("stderr failure");
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let out_tx = tx.clone();
+        let out_task = tokio::spawn(async move {
+            let mut lines = tokio::io::BufReader::new(stdout).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let _ = out_tx.send(StreamedLine::Stdout(line));
+            }
+        });
+        let err_task = tokio::spawn(async move {
+            let mut lines = tokio::io::BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let _ = tx.send(StreamedLine::Stderr(line));
+            }
+        });
+
+        let mut captured = Vec::new();
+        while let Some(line) = rx.recv().await {
+            self.observe_line(line.as_str()).await;
+            captured.push(line);
+        }
+        let _ = tokio::join!(out_task, err_task);
+
+        let status = ps.wait().await?;
+        Ok((status, captured))
+    }
 }
 
 impl<'a> Context<'a> for CliContext {
@@ -36,36 +181,45 @@ impl<'a> Context<'a> for CliContext {
         &self.root
     }
 
-    /// Run a step command
-    /// Right now all output is dumped to stdout/stderr
+    /// Run a step command, streaming + logging its output, discarding it
+    /// once the command completes successfully
     async fn run_command(&self, cmd: &mut Command) -> Result<(), installer::steps::Error> {
-        let status = cmd.spawn()?.wait().await?;
+        let program = cmd.as_std().get_program().to_string_lossy().into_owned();
+        let (status, output) = self.spawn_streamed(cmd, None).await?;
         if !status.success() {
-            let program = cmd.as_std().get_program().to_string_lossy().into();
-            return Err(installer::steps::Error::CommandFailed { program, status });
+            let output = error_context(&output);
+            return Err(installer::steps::Error::CommandFailed { program, status, output });
         }
         Ok(())
     }
 
-    /// Run a astep command, capture stdout
+    /// Run a step command, streaming + logging its output and also
+    /// returning it, stdout and stderr kept separate, once the command
+    /// completes
     async fn run_command_captured(
         &self,
         cmd: &mut Command,
         input: Option<&str>,
     ) -> Result<Output, installer::steps::Error> {
-        cmd.stdin(Stdio::piped());
-        cmd.stdout(Stdio::piped());
-        cmd.stderr(Stdio::piped());
-        let mut ps = cmd.spawn()?;
-        let mut stdin = ps.stdin.take().expect("stdin failure");
-
-        if let Some(input) = input {
-            stdin.write_all(input.as_bytes()).await?;
+        let program = cmd.as_std().get_program().to_string_lossy().into_owned();
+        let (status, output) = self.spawn_streamed(cmd, input).await?;
+        if !status.success() {
+            let output = error_context(&output);
+            return Err(installer::steps::Error::CommandFailed { program, status, output });
         }
-        drop(stdin);
 
-        let output = ps.wait_with_output().await?;
-        Ok(output)
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        for line in &output {
+            match line {
+                StreamedLine::Stdout(line) => stdout.push(line.as_str()),
+                StreamedLine::Stderr(line) => stderr.push(line.as_str()),
+            }
+        }
+        Ok(Output {
+            status,
+            stdout: stdout.join("\n").into_bytes(),
+            stderr: stderr.join("\n").into_bytes(),
+        })
     }
 }
 
@@ -81,6 +235,64 @@ fn print_summary_item(name: &str, item: &impl ToString) {
     println!("      {}   -  {}", style(name).bold(), item.to_string());
 }
 
+/// Build an indeterminate spinner progress bar for `title`/`describe`,
+/// inserted into `multi` just above `total`
+fn spinner_bar(
+    multi: &indicatif::MultiProgress,
+    total: &indicatif::ProgressBar,
+    title: &str,
+    describe: &str,
+) -> indicatif::ProgressBar {
+    let bar = multi.insert_before(
+        total,
+        indicatif::ProgressBar::new(1)
+            .with_message(format!("{} {}", title.blue(), describe.bold()))
+            .with_style(
+                ProgressStyle::with_template(" {spinner} {wide_msg} ")
+                    .unwrap()
+                    .tick_chars("--=≡■≡=--"),
+            ),
+    );
+    bar.enable_steady_tick(Duration::from_millis(150));
+    bar
+}
+
+/// A one-off step that isn't threaded through `Installer::compile_to_steps`
+/// yet, queued up so its contribution to the overall step count is just
+/// the length of the `Vec` it's pushed into, which always matches how
+/// many of them actually run
+struct BoltedStep<'a> {
+    title: String,
+    describe: String,
+    future: std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), installer::steps::Error>> + 'a>>,
+}
+
+impl<'a> BoltedStep<'a> {
+    fn new(
+        title: String,
+        describe: String,
+        future: impl std::future::Future<Output = Result<(), installer::steps::Error>> + 'a,
+    ) -> Self {
+        Self { title, describe, future: Box::pin(future) }
+    }
+}
+
+/// Run a queued-up [`BoltedStep`], showing the same indeterminate
+/// progress bar as a regular step
+async fn run_bolted_step(
+    multi: &indicatif::MultiProgress,
+    total: &indicatif::ProgressBar,
+    context: &CliContext,
+    step: BoltedStep<'_>,
+) -> Result<(), installer::steps::Error> {
+    total.inc(1);
+    let bar = spinner_bar(multi, total, &step.title, &step.describe);
+    context.set_active_bar(Some(bar));
+    let result = step.future.await;
+    context.set_active_bar(None);
+    result
+}
+
 /// Ask the user what locale to use
 async fn ask_locale<'a>(locales: &'a [Locale<'a>]) -> color_eyre::Result<&'a Locale> {
     print_header("🌐", "Now, we need to set the default system locale");
@@ -156,6 +368,50 @@ fn create_user() -> color_eyre::Result<Account> {
         .with_shell("/usr/bin/bash"))
 }
 
+/// Ask the user for a machine hostname
+fn ask_hostname() -> color_eyre::Result<String> {
+    print_header("🖧", "What hostname should this machine use?");
+    let hostname = dialoguer::Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Hostname")
+        .default("serpent".into())
+        .interact_text()?;
+    Ok(hostname)
+}
+
+/// Ask whether to add an IPv6 loopback entry for the hostname
+fn ask_ipv6() -> color_eyre::Result<bool> {
+    dialoguer::Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt("Add an IPv6 (::1) hosts entry for this hostname?")
+        .default(true)
+        .interact()
+        .map_err(Into::into)
+}
+
+/// Ask which network backend should manage this machine's connections
+fn ask_network_backend() -> color_eyre::Result<installer::steps::network::NetworkBackend> {
+    use installer::steps::network::NetworkBackend;
+
+    print_header("🛜", "Which network backend should manage this machine's connections?");
+    let options = [NetworkBackend::NetworkManager, NetworkBackend::SystemdNetworkd];
+    let index = dialoguer::Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Pick a network backend")
+        .items(&options.map(|b| b.to_string()))
+        .default(0)
+        .interact()?;
+    Ok(options[index])
+}
+
+/// Apply a manifest account's password (hashed or plaintext) to `account`
+fn apply_account_password(account: Account, spec: &installer::config::AccountSpec) -> Account {
+    if let Some(hash) = &spec.password_hash {
+        account.with_hashed_password(hash.clone())
+    } else if let Some(password) = &spec.password {
+        account.with_password(password.clone())
+    } else {
+        account
+    }
+}
+
 fn ask_desktop<'a>(desktops: &'a [&Group]) -> color_eyre::Result<&'a selections::Group> {
     print_header("", "What desktop environment do you want to use?");
     let index = dialoguer::Select::with_theme(&ColorfulTheme::default())
@@ -171,11 +427,25 @@ async fn main() -> color_eyre::Result<()> {
     color_eyre::install().unwrap();
     set_colors_enabled(true);
 
+    let cli = Cli::parse();
+
     let euid = unsafe { geteuid() };
     if euid != 0 {
         bail!("lichen must be run as root. Re-run with sudo")
     }
 
+    let manifest = cli.config.as_deref().map(Manifest::load).transpose()?.unwrap_or_default();
+
+    let console = match &cli.console {
+        Some(console) => Some(console.clone()),
+        None => manifest
+            .console
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|err: String| color_eyre::eyre::eyre!(err))?,
+    };
+
     // Test selection management, force GNOME
     let selections = selections::Manager::new().with_groups([
         selections::Group::from_str(include_str!("../../selections/base.json"))?,
@@ -209,30 +479,115 @@ async fn main() -> color_eyre::Result<()> {
     load_spinner.finish_and_clear();
 
     let selected_desktop = ask_desktop(&desktops)?;
-    let selected_locale = ask_locale(&locales).await?;
-    let timezone = ask_timezone()?;
-    let rootpw = ask_password()?;
-    let user_account = create_user()?;
+    let selected_locale = match &manifest.locale {
+        Some(id) => locales
+            .iter()
+            .find(|l| l.id() == id)
+            .ok_or_else(|| color_eyre::eyre::eyre!("unknown locale in manifest: {id}"))?,
+        None => ask_locale(&locales).await?,
+    };
+    let timezone = match &manifest.timezone {
+        Some(tz) => tz.clone(),
+        None => ask_timezone()?,
+    };
+    let hostname = match &manifest.hostname {
+        Some(hostname) => hostname.clone(),
+        None => ask_hostname()?,
+    };
+    let enable_ipv6 = if manifest.hostname.is_some() { manifest.enable_ipv6 } else { ask_ipv6()? };
+    let network_backend = match &manifest.network_backend {
+        Some(backend) => backend.parse().map_err(|err: String| color_eyre::eyre::eyre!(err))?,
+        None => ask_network_backend()?,
+    };
+    let root_account = match manifest.accounts.iter().find(|spec| spec.username == "root") {
+        Some(spec) => {
+            let account = match &spec.shell {
+                Some(shell) => Account::root().with_shell(shell.clone()),
+                None => Account::root(),
+            };
+            apply_account_password(account, spec)
+        }
+        None => Account::root().with_password(ask_password()?),
+    };
+    let mut extra_accounts: Vec<Account> = manifest
+        .accounts
+        .iter()
+        .filter(|spec| spec.username != "root")
+        .map(|spec| {
+            let account = Account::new(spec.username.clone())
+                .with_shell(spec.shell.clone().unwrap_or_else(|| "/usr/bin/bash".into()));
+            apply_account_password(account, spec)
+        })
+        .collect();
+    if extra_accounts.is_empty() {
+        extra_accounts.push(create_user()?);
+    }
 
-    let esp = ask_esp(boots)?;
+    let esp = match &manifest.boot_partition {
+        Some(spec) => boots
+            .iter()
+            .find(|b| b.path == spec.device)
+            .ok_or_else(|| color_eyre::eyre::eyre!("boot partition not found: {}", spec.device.display()))?,
+        None => ask_esp(boots)?,
+    };
 
     // Set / partition
-    let mut rootfs = ask_rootfs(parts)?.clone();
-    rootfs.mountpoint = Some("/".into());
+    let mut rootfs = match &manifest.partition {
+        Some(spec) => parts
+            .iter()
+            .find(|p| p.path == spec.device)
+            .ok_or_else(|| color_eyre::eyre::eyre!("root partition not found: {}", spec.device.display()))?
+            .clone(),
+        None => ask_rootfs(parts)?.clone(),
+    };
+    if let Some(spec) = &manifest.partition {
+        rootfs.filesystem = spec.filesystem.clone();
+    }
+
+    rootfs.mountpoint = manifest
+        .partition
+        .as_ref()
+        .and_then(|spec| spec.mountpoint.clone())
+        .or(Some("/".into()));
 
     print_header("🕮", "Quickly review your settings");
     print_summary_item("Locale", selected_locale);
     print_summary_item("Timezone", &timezone);
+    print_summary_item("Hostname", &hostname);
+    print_summary_item("Network backend", &network_backend);
     print_summary_item("Bootloader", esp);
     print_summary_item("Root (/) filesystem", &rootfs);
+    if let Some(console) = &console {
+        print_summary_item("Serial console", &format!("{console:?}"));
+    }
+
+    let auto_display_manager = desktop::display_manager_for(&selected_desktop.name);
+    let display_manager = manifest
+        .display_manager
+        .clone()
+        .or_else(|| auto_display_manager.map(|dm| dm.selection.to_string()));
+
+    let mut selection_names = if manifest.selections.is_empty() {
+        vec!["develop".into(), selected_desktop.name.clone(), "kernel-desktop".into()]
+    } else {
+        manifest.selections.clone()
+    };
+    if let Some(dm) = &display_manager {
+        selection_names.push(dm.clone());
+    }
+
+    let mut accounts = vec![root_account];
+    accounts.append(&mut extra_accounts);
 
     let model = installer::Model {
-        accounts: [Account::root().with_password(rootpw), user_account].into(),
+        accounts: accounts.clone().into(),
         boot_partition: esp.to_owned(),
         partitions: [rootfs.clone()].into(),
         locale: Some(selected_locale),
         timezone: Some(timezone),
-        packages: selections.selections_with(["develop", &selected_desktop.name, "kernel-desktop"])?,
+        hostname: Some(hostname.clone()),
+        enable_ipv6,
+        packages: selections.selections_with(selection_names.iter().map(String::as_str))?,
     };
     println!("\n\n");
 
@@ -246,12 +601,71 @@ async fn main() -> color_eyre::Result<()> {
     // Push some packages into the installer based on selections
 
     // TODO: Use proper temp directory
-    let context = CliContext {
-        root: "/tmp/lichen".into(),
-    };
-    let (cleanups, steps) = inst.compile_to_steps(&model, &context)?;
     let multi = indicatif::MultiProgress::new();
-    let total = indicatif::ProgressBar::new(steps.len() as u64 + cleanups.len() as u64).with_style(
+    let context = CliContext::new("/tmp/lichen".into(), multi.clone()).await?;
+    let (cleanups, steps) = inst.compile_to_steps(&model, &context)?;
+    // Only enable a display manager unit we picked ourselves: a manifest
+    // override has no known unit to enable.
+    let enable_dm_unit = manifest.display_manager.is_none() && auto_display_manager.is_some();
+
+    // Steps that aren't threaded through `Installer::compile_to_steps` yet,
+    // queued up here instead of run immediately so their share of the
+    // progress bar's total is always just this `Vec`'s length — no
+    // hand-maintained count to keep in sync every time one more gets
+    // bolted on.
+    let mut bolted_steps: Vec<BoltedStep<'_>> = Vec::new();
+    let ctx = &context;
+
+    for account in accounts {
+        let create_account = installer::steps::accounts::CreateAccount::new(account);
+        bolted_steps.push(BoltedStep::new(
+            create_account.title(),
+            create_account.describe(),
+            async move { create_account.execute(ctx).await },
+        ));
+    }
+
+    let hostname_step = installer::steps::network::SetHostname::new(hostname, enable_ipv6);
+    bolted_steps.push(BoltedStep::new(
+        hostname_step.title(),
+        hostname_step.describe(),
+        async move { hostname_step.execute(ctx).await },
+    ));
+
+    for unit in network_backend.units() {
+        let enable_unit = installer::steps::systemd::EnableUnit::new(*unit);
+        bolted_steps.push(BoltedStep::new(
+            enable_unit.title(),
+            enable_unit.describe(),
+            async move { enable_unit.execute(ctx).await },
+        ));
+    }
+
+    if enable_dm_unit {
+        let enable_dm = installer::steps::systemd::EnableUnit::new(auto_display_manager.unwrap().unit);
+        bolted_steps.push(BoltedStep::new(
+            enable_dm.title(),
+            enable_dm.describe(),
+            async move { enable_dm.execute(ctx).await },
+        ));
+    }
+
+    if let Some(console) = console {
+        let configure_console = installer::steps::bootloader::ConfigureConsole::new(
+            installer::steps::bootloader::DEFAULT_CONFIG_PATH,
+            console,
+        );
+        bolted_steps.push(BoltedStep::new(
+            configure_console.title(),
+            configure_console.describe(),
+            async move { configure_console.execute(ctx).await },
+        ));
+    }
+
+    let total = indicatif::ProgressBar::new(
+        steps.len() as u64 + cleanups.len() as u64 + bolted_steps.len() as u64,
+    )
+    .with_style(
         ProgressStyle::with_template("\n|{bar:20.cyan/blue}| {pos}/{len}")
             .unwrap()
             .progress_chars("■≡=- "),
@@ -261,24 +675,20 @@ async fn main() -> color_eyre::Result<()> {
     for step in steps {
         total.inc(1);
         if step.is_indeterminate() {
-            let progress_bar = multi.insert_before(
-                &total,
-                indicatif::ProgressBar::new(1)
-                    .with_message(format!("{} {}", step.title().blue(), step.describe().bold(),))
-                    .with_style(
-                        ProgressStyle::with_template(" {spinner} {wide_msg} ")
-                            .unwrap()
-                            .tick_chars("--=≡■≡=--"),
-                    ),
-            );
-            progress_bar.enable_steady_tick(Duration::from_millis(150));
+            let progress_bar = spinner_bar(&multi, &total, &step.title(), &step.describe());
+            context.set_active_bar(Some(progress_bar));
             step.execute(&context).await?;
+            context.set_active_bar(None);
         } else {
             multi.println(format!("{} {}", step.title().blue(), step.describe().bold()))?;
             multi.suspend(|| step.execute(&context)).await?;
         }
     }
 
+    for step in bolted_steps {
+        run_bolted_step(&multi, &total, &context, step).await?;
+    }
+
     // Execute all the cleanups
     for cleanup in cleanups {
         let progress_bar = multi.insert_before(
@@ -293,7 +703,9 @@ async fn main() -> color_eyre::Result<()> {
         );
         progress_bar.enable_steady_tick(Duration::from_millis(150));
         total.inc(1);
+        context.set_active_bar(Some(progress_bar));
         cleanup.execute(&context).await?;
+        context.set_active_bar(None);
     }
 
     multi.clear()?;